@@ -1,13 +1,17 @@
+mod serve;
+
 use clap::Parser;
-use lotabots_core::{ModelConfig, ModelQuantizer, PyTorchQuantizer};
+use lotabots_core::quantizer::PyTorchQuantizer;
+use lotabots_core::{gpu, Device, ModelFetcher, ModelQuantizer, ModelUploader, QuantizationConfig};
 use lotabots_whatsapp::{TwilioClient, AppState, SharedState, create_router};
+use serve::{build_generate_router, InferenceEngine};
 use std::sync::Arc;
 use tracing::{info, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use dotenv::dotenv;
 use std::{env, path::PathBuf};
 use redis::Client as RedisClient;
-use axum::Server;
+use axum::{Router, Server};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -29,6 +33,15 @@ struct Args {
 
     #[arg(long, help = "Run as a WhatsApp bot")]
     whatsapp: bool,
+
+    #[arg(long, help = "Serve a /generate inference API for a quantized model")]
+    serve: bool,
+
+    #[arg(long, help = "Path to the quantized .pt model to serve (required with --serve)")]
+    model_path: Option<String>,
+
+    #[arg(long, help = "Path to the tokenizer.json to use when serving (defaults next to model_path)")]
+    tokenizer_path: Option<String>,
 }
 
 #[tokio::main]
@@ -46,12 +59,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let args = Args::parse();
 
-    if args.whatsapp {
-        run_whatsapp_bot().await?;
+    if args.whatsapp || args.serve {
+        run_server(args).await?;
     } else if args.model.is_some() && args.output.is_some() && args.bits.is_some() {
         run_quantization(args).await?;
     } else {
-        println!("Please provide the required arguments or use --whatsapp for bot mode.");
+        println!("Please provide the required arguments, use --whatsapp for bot mode, or --serve for the inference API.");
     }
 
     Ok(())
@@ -74,43 +87,75 @@ async fn run_quantization(args: Args) -> Result<(), Box<dyn std::error::Error>>
 
     info!("Starting model quantization for {} to {} bits", model_id, bits);
     info!("Using cache directory: {}", cache_dir.display());
-    info!("Using API token: {}", api_token);
 
-    let config = ModelConfig {
-        model_id,
-        cache_dir,
-        quantization_bits: bits,
+    let dest = cache_dir.join(model_id.replace('/', "_"));
+    let detected = gpu::detect_gpus()?;
+    let device = detected.first().copied().unwrap_or(Device::CPU);
+    let devices = if detected.is_empty() { vec![device] } else { detected };
+    let config = QuantizationConfig {
+        bits,
+        mixed_precision: false,
+        device,
+        devices,
+        params: Default::default(),
     };
 
-    let quantizer = PyTorchQuantizer::new(api_token.clone());
-    let quantized_path = quantizer.fetch_and_quantize(&config).await?;
-    info!("Model quantized successfully to {:?}", quantized_path);
+    let quantizer = PyTorchQuantizer::new(api_token);
+    let model = quantizer.fetch(&model_id, &dest).await?;
+    info!("Model fetched to {:?}", model.path);
+
+    let quantized = quantizer.quantize(&model, config).await?;
+    info!("Model quantized successfully to {:?}", quantized.path);
 
     info!("Uploading quantized model to {}", output_repo);
-    quantizer.upload_model(&quantized_path, &output_repo).await?;
+    quantizer.upload(&quantized, &output_repo).await?;
     info!("Model uploaded successfully!");
 
     println!("Model quantization and upload completed successfully!");
     Ok(())
 }
 
-async fn run_whatsapp_bot() -> Result<(), Box<dyn std::error::Error>> {
-    info!("Starting WhatsApp bot...");
+/// Build and run the HTTP server, merging in the WhatsApp webhook router
+/// and/or the inference `/generate` router depending on which modes were
+/// requested. Both can run side by side on the same port.
+async fn run_server(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut app = Router::new();
 
-    let twilio_auth_token = env::var("TWILIO_AUTH_TOKEN")
-        .expect("TWILIO_AUTH_TOKEN must be set");
-    let redis_url = env::var("REDIS_URL")
-        .expect("REDIS_URL must be set");
-    
-    let redis_client = RedisClient::open(redis_url)
-        .expect("Failed to create redis client");
+    if args.whatsapp {
+        info!("Enabling WhatsApp bot routes...");
+
+        let twilio_auth_token = env::var("TWILIO_AUTH_TOKEN")
+            .expect("TWILIO_AUTH_TOKEN must be set");
+        let redis_url = env::var("REDIS_URL")
+            .expect("REDIS_URL must be set");
 
-    let app_state = Arc::new(AppState {
-        twilio_client: TwilioClient::new(twilio_auth_token),
-        redis_client,
-    });
+        let redis_client = RedisClient::open(redis_url)
+            .expect("Failed to create redis client");
 
-    let app = create_router(app_state);
+        let app_state = Arc::new(AppState {
+            twilio_client: TwilioClient::new(twilio_auth_token),
+            redis_client,
+        });
+
+        app = app.merge(create_router(app_state));
+    }
+
+    if args.serve {
+        info!("Enabling inference /generate route...");
+
+        let model_path = PathBuf::from(
+            args.model_path.expect("--model-path is required with --serve"),
+        );
+        let tokenizer_path = args
+            .tokenizer_path
+            .map(PathBuf::from)
+            .unwrap_or_else(|| model_path.with_file_name("tokenizer.json"));
+
+        let device = gpu::detect_gpus()?.into_iter().next().unwrap_or(lotabots_core::Device::CPU);
+        let engine = Arc::new(InferenceEngine::load(&model_path, &tokenizer_path, device)?);
+
+        app = app.merge(build_generate_router(engine));
+    }
 
     let port = env::var("PORT")
         .unwrap_or_else(|_| "3000".to_string())
@@ -124,4 +169,4 @@ async fn run_whatsapp_bot() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
 
     Ok(())
-} 
\ No newline at end of file
+}
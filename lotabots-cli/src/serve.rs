@@ -0,0 +1,254 @@
+//! Inference-serving mode: load a quantized model and expose a `/generate`
+//! HTTP endpoint that streams sampled tokens back to the caller.
+
+use axum::extract::State;
+use axum::response::sse::{Event, Sse};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::{Json, Router};
+use futures_util::stream;
+use lotabots_core::{Device, ModelError};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc;
+use tracing::info;
+
+const DEFAULT_MAX_TOKENS: usize = 256;
+const DEFAULT_TEMPERATURE: f32 = 0.8;
+const DEFAULT_TOP_P: f32 = 0.95;
+const DEFAULT_REPEAT_PENALTY: f32 = 1.1;
+
+/// Request body for `POST /generate`.
+#[derive(Debug, Deserialize)]
+pub struct GenerateRequest {
+    pub prompt: String,
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeneratedToken {
+    token: String,
+    done: bool,
+}
+
+/// Divide the logit of every already-generated token by `repeat_penalty`
+/// (and multiply instead when the logit is negative, matching llama.cpp's
+/// convention of always pushing the logit towards zero).
+fn apply_repeat_penalty(logits: &mut [f32], generated: &[u32], repeat_penalty: f32) {
+    if repeat_penalty == 1.0 {
+        return;
+    }
+    let mut seen = std::collections::HashSet::new();
+    for &id in generated {
+        if !seen.insert(id) {
+            continue;
+        }
+        if let Some(logit) = logits.get_mut(id as usize) {
+            *logit = if *logit > 0.0 {
+                *logit / repeat_penalty
+            } else {
+                *logit * repeat_penalty
+            };
+        }
+    }
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|&l| (l - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+    exp.into_iter().map(|e| e / sum).collect()
+}
+
+/// Sample the next token id from `logits`: repeat penalty, then temperature
+/// scaling and nucleus (top-p) sampling. `temperature == 0.0` short-circuits
+/// to greedy argmax.
+fn sample_token(
+    logits: &mut [f32],
+    generated: &[u32],
+    temperature: f32,
+    top_p: f32,
+    repeat_penalty: f32,
+    rng: &mut StdRng,
+) -> u32 {
+    apply_repeat_penalty(logits, generated, repeat_penalty);
+
+    if temperature == 0.0 {
+        return logits
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(idx, _)| idx as u32)
+            .unwrap_or(0);
+    }
+
+    let scaled: Vec<f32> = logits.iter().map(|&l| l / temperature).collect();
+    let probs = softmax(&scaled);
+
+    let mut ranked: Vec<(u32, f32)> = probs.iter().enumerate().map(|(i, &p)| (i as u32, p)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut cumulative = 0.0;
+    let mut nucleus_end = ranked.len();
+    for (i, &(_, p)) in ranked.iter().enumerate() {
+        cumulative += p;
+        if cumulative >= top_p {
+            nucleus_end = i + 1;
+            break;
+        }
+    }
+    let nucleus = &ranked[..nucleus_end];
+    let nucleus_total: f32 = nucleus.iter().map(|&(_, p)| p).sum();
+
+    let target = rng.gen::<f32>() * nucleus_total;
+    let mut acc = 0.0;
+    for &(id, p) in nucleus {
+        acc += p;
+        if acc >= target {
+            return id;
+        }
+    }
+    nucleus.last().map(|&(id, _)| id).unwrap_or(0)
+}
+
+/// A loaded, quantized model ready to run token-by-token generation.
+///
+/// Serving GGUF artifacts directly is out of scope for this engine: doing so
+/// would mean embedding a llama.cpp-style execution graph rather than reusing
+/// `tch`, which is a substantially bigger change than this inference mode set
+/// out to make. Only the `.quantized_N_bit.pt` artifacts produced by
+/// [`lotabots_core::quantizer::PyTorchQuantizer`] can be served; a GGUF output
+/// from [`lotabots_core::gguf::GgufQuantizer`] must be re-quantized to that
+/// format first.
+pub struct InferenceEngine {
+    module: tch::CModule,
+    tokenizer: Tokenizer,
+    device: tch::Device,
+    eos_token_id: u32,
+}
+
+impl InferenceEngine {
+    pub fn load(model_path: &Path, tokenizer_path: &Path, device: Device) -> Result<Self, ModelError> {
+        let tch_device = match device {
+            Device::CPU => tch::Device::Cpu,
+            Device::CUDA(ordinal) => tch::Device::Cuda(ordinal),
+            Device::ROCm(_) => {
+                return Err(ModelError::QuantizationError(
+                    "serving mode has no ROCm backend yet".into(),
+                ))
+            }
+        };
+
+        if model_path.extension().and_then(|e| e.to_str()) == Some("gguf") {
+            return Err(ModelError::QuantizationError(
+                "GGUF serving is out of scope for this engine; serve a .quantized_N_bit.pt artifact instead".into(),
+            ));
+        }
+
+        let module = tch::CModule::load_on_device(model_path, tch_device)
+            .map_err(|e| ModelError::QuantizationError(format!("failed to load model: {e}")))?;
+        let tokenizer = Tokenizer::from_file(tokenizer_path)
+            .map_err(|e| ModelError::QuantizationError(format!("failed to load tokenizer: {e}")))?;
+        let eos_token_id = tokenizer.token_to_id("</s>").unwrap_or(2);
+
+        Ok(Self { module, tokenizer, device: tch_device, eos_token_id })
+    }
+
+    fn forward(&self, context: &[u32]) -> Result<Vec<f32>, ModelError> {
+        let ids: Vec<i64> = context.iter().map(|&id| id as i64).collect();
+        let input = tch::Tensor::from_slice(&ids).unsqueeze(0).to_device(self.device);
+        let logits = self
+            .module
+            .forward_ts(&[input])
+            .map_err(|e| ModelError::QuantizationError(format!("forward pass failed: {e}")))?;
+
+        // Logits for the last position in the sequence, as a flat f32 vec.
+        let last = logits.select(1, logits.size()[1] - 1);
+        Vec::<f32>::try_from(last.to_kind(tch::Kind::Float))
+            .map_err(|e| ModelError::QuantizationError(format!("failed to read logits: {e}")))
+    }
+
+    /// Generate up to `max_tokens` tokens continuing `prompt`, pushing each
+    /// one to `tx` as soon as it's sampled (rather than buffering the whole
+    /// response) so the caller can stream it straight through to the client.
+    /// Stops early, without error, if the receiving end has gone away.
+    fn generate(&self, request: &GenerateRequest, tx: &mpsc::Sender<GeneratedToken>) -> Result<(), ModelError> {
+        let max_tokens = request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        let temperature = request.temperature.unwrap_or(DEFAULT_TEMPERATURE);
+        let top_p = request.top_p.unwrap_or(DEFAULT_TOP_P);
+        let repeat_penalty = request.repeat_penalty.unwrap_or(DEFAULT_REPEAT_PENALTY);
+
+        let encoding = self
+            .tokenizer
+            .encode(request.prompt.as_str(), true)
+            .map_err(|e| ModelError::QuantizationError(format!("tokenization failed: {e}")))?;
+        let mut context: Vec<u32> = encoding.get_ids().to_vec();
+        let prompt_len = context.len();
+
+        let mut rng = StdRng::from_entropy();
+
+        for _ in 0..max_tokens {
+            let mut logits = self.forward(&context)?;
+            let generated = &context[prompt_len..];
+            let next = sample_token(&mut logits, generated, temperature, top_p, repeat_penalty, &mut rng);
+
+            if next == self.eos_token_id {
+                break;
+            }
+
+            let piece = self
+                .tokenizer
+                .decode(&[next], true)
+                .map_err(|e| ModelError::QuantizationError(format!("detokenization failed: {e}")))?;
+            context.push(next);
+
+            if tx.blocking_send(GeneratedToken { token: piece, done: false }).is_err() {
+                // The client disconnected and dropped the receiver; stop
+                // spending compute on a response nobody is reading.
+                return Ok(());
+            }
+        }
+
+        let _ = tx.blocking_send(GeneratedToken { token: String::new(), done: true });
+        Ok(())
+    }
+}
+
+async fn generate_handler(
+    State(engine): State<Arc<InferenceEngine>>,
+    Json(request): Json<GenerateRequest>,
+) -> impl IntoResponse {
+    info!("Generating for prompt of {} chars", request.prompt.len());
+
+    let (tx, rx) = mpsc::channel::<GeneratedToken>(8);
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = engine.generate(&request, &tx) {
+            let _ = tx.blocking_send(GeneratedToken { token: format!("[error: {e}]"), done: true });
+        }
+    });
+
+    // Turn the channel into an SSE stream that yields each token to the
+    // client as soon as `generate` samples it, instead of waiting for the
+    // whole response to buffer up first.
+    let stream = stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|token| (Ok::<_, Infallible>(Event::default().json_data(token).unwrap()), rx))
+    });
+
+    Sse::new(stream)
+}
+
+/// Build the `/generate` route, to be merged into the process' main router.
+pub fn build_generate_router(engine: Arc<InferenceEngine>) -> Router {
+    Router::new().route("/generate", post(generate_handler)).with_state(engine)
+}
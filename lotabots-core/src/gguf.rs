@@ -0,0 +1,479 @@
+//! GGUF quantization backend built on the `candle` ecosystem.
+//!
+//! Unlike [`crate::quantizer::PyTorchQuantizer`], this backend never touches
+//! libtorch: it loads safetensors weights through `candle-core` and writes
+//! them out in llama.cpp's block-quantized tensor layouts (Q4_0/Q8_0/Q4_K),
+//! GGUF container framing, and `ne[]` dimension order. It does not emit the
+//! architecture hyperparameter or tokenizer KVs a specific runtime (e.g.
+//! llama.cpp) needs to build a model graph from the file, since those are
+//! architecture-specific and this quantizer works over arbitrary safetensors
+//! tensors with no knowledge of the source model's architecture — so the
+//! output is not yet a drop-in `.gguf` for those runtimes as-is.
+
+use crate::{Device, Model, ModelError, ModelQuantizer, QuantizationConfig};
+use async_trait::async_trait;
+use candle_core::Device as CandleDevice;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Number of weights per Q4_0 / Q8_0 block.
+const QK4_0: usize = 32;
+const QK8_0: usize = 32;
+/// Number of weights per Q4_K super-block (8 sub-blocks of 32).
+const QK_K: usize = 256;
+const QK_K_SUBBLOCKS: usize = QK_K / 32;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const GGUF_VERSION: u32 = 3;
+
+/// GGUF tensor quantization types we can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GgufType {
+    Q4_0,
+    Q8_0,
+    Q4K,
+}
+
+impl GgufType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Q4_0" => Some(Self::Q4_0),
+            "Q8_0" => Some(Self::Q8_0),
+            "Q4_K" => Some(Self::Q4K),
+            _ => None,
+        }
+    }
+
+    /// GGUF's own numeric identifier for this quantization type (ggml_type).
+    fn ggml_type_id(self) -> u32 {
+        match self {
+            GgufType::Q4_0 => 2,
+            GgufType::Q8_0 => 8,
+            GgufType::Q4K => 12,
+        }
+    }
+
+    fn block_size(self) -> usize {
+        match self {
+            GgufType::Q4_0 | GgufType::Q8_0 => 32,
+            GgufType::Q4K => QK_K,
+        }
+    }
+
+    /// Map `QuantizationConfig.params["gguf_type"]` to a type, falling back
+    /// to a sensible default derived from `bits`.
+    fn from_config(config: &QuantizationConfig) -> Self {
+        config
+            .params
+            .get("gguf_type")
+            .and_then(|s| Self::from_name(s))
+            .unwrap_or_else(|| match config.bits {
+                4 => GgufType::Q4K,
+                8 => GgufType::Q8_0,
+                _ => GgufType::Q4_0,
+            })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GgufType::Q4_0 => "Q4_0",
+            GgufType::Q8_0 => "Q8_0",
+            GgufType::Q4K => "Q4_K",
+        }
+    }
+}
+
+/// Quantize a single Q4_0 block of up to [`QK4_0`] weights: an f16 scale
+/// followed by packed 4-bit signed indices in `[-8, 7]`.
+fn quantize_block_q4_0(block: &[f32], out: &mut Vec<u8>) {
+    let amax = block.iter().fold(0f32, |m, &w| m.max(w.abs()));
+    let scale = amax / -8.0;
+    out.extend_from_slice(&half::f16::from_f32(scale).to_le_bytes());
+
+    let quant = |w: f32| -> i8 {
+        if scale == 0.0 {
+            0
+        } else {
+            (w / scale).round().clamp(-8.0, 7.0) as i8
+        }
+    };
+
+    // llama.cpp packs each byte from two weights `QK4_0/2` apart (element
+    // `j` in the low nibble, `j + QK4_0/2` in the high nibble), not adjacent
+    // pairs, so a dequantizer can unpack both halves of the block with a
+    // single strided pass.
+    let half = QK4_0 / 2;
+    for j in 0..half {
+        let lo = (quant(block[j]) + 8) as u8 & 0x0F;
+        let hi = (quant(block[j + half]) + 8) as u8 & 0x0F;
+        out.push(lo | (hi << 4));
+    }
+}
+
+/// Quantize a single Q8_0 block of up to [`QK8_0`] weights: an f16 scale
+/// followed by signed 8-bit indices.
+fn quantize_block_q8_0(block: &[f32], out: &mut Vec<u8>) {
+    let amax = block.iter().fold(0f32, |m, &w| m.max(w.abs()));
+    let scale = amax / 127.0;
+    out.extend_from_slice(&half::f16::from_f32(scale).to_le_bytes());
+
+    for &w in block {
+        let q = if scale == 0.0 {
+            0
+        } else {
+            (w / scale).round().clamp(-128.0, 127.0) as i8
+        };
+        out.push(q as u8);
+    }
+}
+
+/// Pack the 8 6-bit sub-block scales and 8 6-bit sub-block mins into ggml's
+/// 12-byte `scales` array, matching the bit layout `get_scale_min_k4` in
+/// llama.cpp unpacks: the first 4 sub-blocks store their scale/min directly
+/// in the low 6 bits of bytes `0..4`/`4..8`, and the last 4 sub-blocks split
+/// their scale/min across the high 2 bits of those same bytes and a nibble
+/// each in bytes `8..12`.
+fn pack_q4_k_scales(scales: &[u8; QK_K_SUBBLOCKS], mins: &[u8; QK_K_SUBBLOCKS]) -> [u8; 12] {
+    let mut q = [0u8; 12];
+    for jj in 0..4 {
+        q[jj] = (scales[jj] & 0x3F) | ((scales[4 + jj] >> 4) << 6);
+        q[4 + jj] = (mins[jj] & 0x3F) | ((mins[4 + jj] >> 4) << 6);
+        q[8 + jj] = (scales[4 + jj] & 0x0F) | ((mins[4 + jj] & 0x0F) << 4);
+    }
+    q
+}
+
+/// Quantize a single Q4_K super-block of up to [`QK_K`] weights: a super-block
+/// `d`/`dmin` pair in f16, 6-bit packed per-sub-block scales and mins (ggml's
+/// 12-byte `scales` layout), then the 4-bit weights.
+fn quantize_block_q4_k(block: &[f32], out: &mut Vec<u8>) {
+    let mut sub_scales = [0f32; QK_K_SUBBLOCKS];
+    let mut sub_mins = [0f32; QK_K_SUBBLOCKS];
+
+    for (i, sub) in block.chunks(32).enumerate() {
+        let min = sub.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = sub.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        sub_mins[i] = min;
+        sub_scales[i] = (max - min) / 15.0;
+    }
+
+    let d = sub_scales.iter().cloned().fold(0f32, f32::max) / 63.0;
+    let dmin = sub_mins.iter().cloned().fold(0f32, |m, v| m.max(v.abs())) / 63.0;
+
+    out.extend_from_slice(&half::f16::from_f32(d).to_le_bytes());
+    out.extend_from_slice(&half::f16::from_f32(dmin).to_le_bytes());
+
+    let mut packed_scales = [0u8; QK_K_SUBBLOCKS];
+    let mut packed_mins = [0u8; QK_K_SUBBLOCKS];
+    for i in 0..QK_K_SUBBLOCKS {
+        packed_scales[i] = if d == 0.0 {
+            0
+        } else {
+            (sub_scales[i] / d).round().clamp(0.0, 63.0) as u8
+        };
+        packed_mins[i] = if dmin == 0.0 {
+            0
+        } else {
+            (sub_mins[i].abs() / dmin).round().clamp(0.0, 63.0) as u8
+        };
+    }
+    out.extend_from_slice(&pack_q4_k_scales(&packed_scales, &packed_mins));
+
+    let quant = |i: usize, w: f32| -> u8 {
+        let scale = packed_scales[i] as f32 * d;
+        let min = -(packed_mins[i] as f32 * dmin);
+        if scale == 0.0 {
+            0
+        } else {
+            ((w - min) / scale).round().clamp(0.0, 15.0) as u8
+        }
+    };
+    // llama.cpp pairs sub-blocks: within each 64-weight group, byte `l`
+    // holds element `l` of the even sub-block in its low nibble and element
+    // `l` of the following odd sub-block in its high nibble.
+    for pair in 0..QK_K_SUBBLOCKS / 2 {
+        let (lo_sub, hi_sub) = (2 * pair, 2 * pair + 1);
+        for l in 0..32 {
+            let lo = quant(lo_sub, block[lo_sub * 32 + l]) & 0x0F;
+            let hi = quant(hi_sub, block[hi_sub * 32 + l]) & 0x0F;
+            out.push(lo | (hi << 4));
+        }
+    }
+}
+
+/// Quantize a flat row of weights into the byte layout for `gguf_type`,
+/// padding the final partial block with zeros.
+fn quantize_row(data: &[f32], gguf_type: GgufType) -> Vec<u8> {
+    let block_size = gguf_type.block_size();
+    let mut out = Vec::new();
+    for chunk in data.chunks(block_size) {
+        let mut padded;
+        let block = if chunk.len() == block_size {
+            chunk
+        } else {
+            padded = chunk.to_vec();
+            padded.resize(block_size, 0.0);
+            &padded
+        };
+        match gguf_type {
+            GgufType::Q4_0 => quantize_block_q4_0(block, &mut out),
+            GgufType::Q8_0 => quantize_block_q8_0(block, &mut out),
+            GgufType::Q4K => quantize_block_q4_k(block, &mut out),
+        }
+    }
+    out
+}
+
+struct GgufTensor {
+    name: String,
+    /// Dimensions in ggml's `ne[]` order: fastest-moving dimension first,
+    /// i.e. the reverse of the row-major shape `candle`/safetensors report.
+    shape: Vec<u64>,
+    gguf_type: GgufType,
+    data: Vec<u8>,
+}
+
+/// A GGUF KV metadata value, tagged with its GGUF type id so callers can't
+/// accidentally write e.g. `general.file_type` as a string when llama.cpp
+/// reads it as `uint32`.
+enum GgufMetadataValue<'a> {
+    String(&'a str),
+    Uint32(u32),
+}
+
+/// Write a GGUF file containing `tensors`, tagged with `metadata` key/value
+/// pairs (e.g. `general.architecture`, `general.quantization_version`).
+fn write_gguf(
+    path: &Path,
+    tensors: &[GgufTensor],
+    metadata: &[(&str, GgufMetadataValue)],
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(GGUF_MAGIC);
+    buf.extend_from_slice(&GGUF_VERSION.to_le_bytes());
+    buf.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+
+    let write_str = |buf: &mut Vec<u8>, s: &str| {
+        buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    };
+
+    const GGUF_TYPE_UINT32: u32 = 4;
+    const GGUF_TYPE_STRING: u32 = 8;
+    for (key, value) in metadata {
+        write_str(&mut buf, key);
+        match value {
+            GgufMetadataValue::String(s) => {
+                buf.extend_from_slice(&GGUF_TYPE_STRING.to_le_bytes());
+                write_str(&mut buf, s);
+            }
+            GgufMetadataValue::Uint32(n) => {
+                buf.extend_from_slice(&GGUF_TYPE_UINT32.to_le_bytes());
+                buf.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+    }
+
+    // Tensor info section, tracking data offsets relative to the aligned
+    // tensor-data region that follows.
+    const ALIGNMENT: u64 = 32;
+    let mut offset = 0u64;
+    let mut offsets = Vec::with_capacity(tensors.len());
+    for t in tensors {
+        offsets.push(offset);
+        offset += t.data.len() as u64;
+        offset = (offset + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT;
+    }
+
+    for (t, &tensor_offset) in tensors.iter().zip(&offsets) {
+        write_str(&mut buf, &t.name);
+        buf.extend_from_slice(&(t.shape.len() as u32).to_le_bytes());
+        for dim in &t.shape {
+            buf.extend_from_slice(&dim.to_le_bytes());
+        }
+        buf.extend_from_slice(&t.gguf_type.ggml_type_id().to_le_bytes());
+        buf.extend_from_slice(&tensor_offset.to_le_bytes());
+    }
+
+    // Pad header to the alignment boundary before tensor data begins.
+    while buf.len() % ALIGNMENT as usize != 0 {
+        buf.push(0);
+    }
+
+    for t in tensors {
+        let start = buf.len();
+        buf.extend_from_slice(&t.data);
+        while (buf.len() - start) % ALIGNMENT as usize != 0 {
+            buf.push(0);
+        }
+    }
+
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&buf)
+}
+
+/// Candle-based GGUF quantizer. Reads safetensors weights and emits a `.gguf`
+/// file using llama.cpp's tensor and container layout, with no dependency on
+/// libtorch — see the module docs for what's still missing for a specific
+/// runtime to load the result directly.
+pub struct GgufQuantizer;
+
+impl GgufQuantizer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for GgufQuantizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ModelQuantizer for GgufQuantizer {
+    async fn quantize(&self, model: &Model, config: QuantizationConfig) -> Result<Model, ModelError> {
+        let gguf_type = GgufType::from_config(&config);
+        info!("Quantizing {:?} to GGUF {} ", model.path, gguf_type.name());
+
+        let candle_device = match config.device {
+            Device::CPU => CandleDevice::Cpu,
+            Device::CUDA(ordinal) => CandleDevice::new_cuda(ordinal)
+                .map_err(|e| ModelError::QuantizationError(format!("candle CUDA init failed: {e}")))?,
+            Device::ROCm(_) => {
+                return Err(ModelError::QuantizationError(
+                    "ROCm is not yet supported by the candle GGUF backend".into(),
+                ))
+            }
+        };
+
+        let tensors = candle_core::safetensors::load(&model.path, &candle_device)
+            .map_err(|e| ModelError::QuantizationError(format!("failed to load safetensors: {e}")))?;
+
+        let mut gguf_tensors = Vec::with_capacity(tensors.len());
+        for (name, tensor) in tensors {
+            // ggml's `ne[]` lists the fastest-moving dimension first, the
+            // reverse of the row-major shape candle reports.
+            let shape: Vec<u64> = tensor.dims().iter().rev().map(|&d| d as u64).collect();
+            let flat = tensor
+                .to_dtype(candle_core::DType::F32)
+                .and_then(|t| t.flatten_all())
+                .and_then(|t| t.to_vec1::<f32>())
+                .map_err(|e| ModelError::QuantizationError(format!("tensor {name}: {e}")))?;
+
+            gguf_tensors.push(GgufTensor {
+                name,
+                shape,
+                gguf_type,
+                data: quantize_row(&flat, gguf_type),
+            });
+        }
+
+        let output_path: PathBuf = model.path.with_extension(format!("{}.gguf", gguf_type.name().to_lowercase()));
+        let metadata = [
+            ("general.architecture", GgufMetadataValue::String("llama")),
+            ("general.quantization_version", GgufMetadataValue::Uint32(2)),
+            ("general.file_type", GgufMetadataValue::Uint32(gguf_type.ggml_type_id())),
+        ];
+        write_gguf(&output_path, &gguf_tensors, &metadata)
+            .map_err(|e| ModelError::QuantizationError(format!("failed to write GGUF file: {e}")))?;
+
+        Ok(Model {
+            id: model.id.clone(),
+            name: model.name.clone(),
+            path: output_path,
+            format: "gguf".to_string(),
+            size: std::fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0),
+            shards: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn q4_0_roundtrips_within_quantization_error() {
+        let block: Vec<f32> = (0..QK4_0).map(|i| (i as f32 - 16.0) / 2.0).collect();
+        let mut out = Vec::new();
+        quantize_block_q4_0(&block, &mut out);
+        // f16 scale + 16 packed bytes for 32 nibbles.
+        assert_eq!(out.len(), 2 + QK4_0 / 2);
+
+        // Decode using llama.cpp's split-nibble layout (element `j` in the
+        // low nibble, `j + QK4_0/2` in the high nibble) and check we
+        // recover values close to the originals, not just a matching length.
+        let scale = half::f16::from_le_bytes([out[0], out[1]]).to_f32();
+        let half_len = QK4_0 / 2;
+        let mut decoded = vec![0f32; QK4_0];
+        for j in 0..half_len {
+            let byte = out[2 + j];
+            decoded[j] = ((byte & 0x0F) as i8 - 8) as f32 * scale;
+            decoded[j + half_len] = (((byte >> 4) & 0x0F) as i8 - 8) as f32 * scale;
+        }
+        for (original, decoded) in block.iter().zip(decoded.iter()) {
+            assert!(
+                (original - decoded).abs() <= scale.abs() + f32::EPSILON,
+                "original {original} decoded {decoded} scale {scale}"
+            );
+        }
+    }
+
+    #[test]
+    fn q8_0_block_size_matches_spec() {
+        let block = vec![1.0f32; QK8_0];
+        let mut out = Vec::new();
+        quantize_block_q8_0(&block, &mut out);
+        assert_eq!(out.len(), 2 + QK8_0);
+    }
+
+    #[test]
+    fn q4_k_super_block_size_matches_spec() {
+        let block: Vec<f32> = (0..QK_K).map(|i| i as f32 / 100.0).collect();
+        let mut out = Vec::new();
+        quantize_block_q4_k(&block, &mut out);
+        // d + dmin (2 x f16) + 12-byte 6-bit-packed scales/mins + 128 nibble bytes.
+        assert_eq!(out.len(), 4 + 12 + QK_K / 2);
+    }
+
+    /// Inverse of [`pack_q4_k_scales`], mirroring llama.cpp's
+    /// `get_scale_min_k4`, to check the packing round-trips exactly.
+    fn unpack_q4_k_scales(q: &[u8; 12]) -> ([u8; QK_K_SUBBLOCKS], [u8; QK_K_SUBBLOCKS]) {
+        let mut scales = [0u8; QK_K_SUBBLOCKS];
+        let mut mins = [0u8; QK_K_SUBBLOCKS];
+        for jj in 0..4 {
+            scales[jj] = q[jj] & 0x3F;
+            mins[jj] = q[4 + jj] & 0x3F;
+            scales[4 + jj] = (q[8 + jj] & 0x0F) | ((q[jj] >> 6) << 4);
+            mins[4 + jj] = (q[8 + jj] >> 4) | ((q[4 + jj] >> 6) << 4);
+        }
+        (scales, mins)
+    }
+
+    #[test]
+    fn q4_k_scale_min_packing_roundtrips() {
+        let scales: [u8; QK_K_SUBBLOCKS] = [63, 1, 32, 0, 63, 17, 45, 9];
+        let mins: [u8; QK_K_SUBBLOCKS] = [0, 63, 5, 33, 62, 3, 28, 40];
+        let packed = pack_q4_k_scales(&scales, &mins);
+        assert_eq!(packed.len(), 12);
+        let (decoded_scales, decoded_mins) = unpack_q4_k_scales(&packed);
+        assert_eq!(decoded_scales, scales);
+        assert_eq!(decoded_mins, mins);
+    }
+
+    #[test]
+    fn default_gguf_type_follows_bits() {
+        let config = QuantizationConfig {
+            bits: 4,
+            mixed_precision: false,
+            device: Device::CPU,
+            devices: vec![Device::CPU],
+            params: HashMap::new(),
+        };
+        assert_eq!(GgufType::from_config(&config), GgufType::Q4K);
+    }
+}
@@ -5,6 +5,10 @@ use async_trait::async_trait;
 use thiserror::Error;
 use sysinfo::System;
 
+pub mod gguf;
+pub mod quantizer;
+pub mod sharding;
+
 /// Errors that can occur during model operations
 #[derive(Debug, Error)]
 pub enum ModelError {
@@ -44,6 +48,12 @@ pub struct Model {
 
     /// Model size in bytes
     pub size: u64,
+
+    /// For repos split into multiple shard files (e.g. a safetensors index
+    /// with `model-NNNNN-of-MMMMM.safetensors` parts), the path of each
+    /// shard file, i.e. `path` joined with the shard's filename. Empty for
+    /// single-file models.
+    pub shards: Vec<PathBuf>,
 }
 
 /// Trait for fetching models from remote sources
@@ -76,67 +86,95 @@ pub struct QuantizationConfig {
     /// Whether to use mixed precision
     pub mixed_precision: bool,
 
-    /// Target device (CPU, CUDA, ROCm)
+    /// Primary/default target device (CPU, CUDA, ROCm)
     pub device: Device,
 
+    /// All devices available for sharded quantization. When this holds more
+    /// than one entry, [`sharding`] distributes the model's weight tensors
+    /// across them; a single entry (the common case) preserves the previous
+    /// single-device behavior.
+    pub devices: Vec<Device>,
+
     /// Additional quantization parameters
     pub params: std::collections::HashMap<String, String>,
 }
 
-/// Supported compute devices
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Supported compute devices, carrying the ordinal of the physical GPU they
+/// refer to (ignored for `CPU`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Device {
     CPU,
-    CUDA,
-    ROCm,
+    CUDA(usize),
+    ROCm(usize),
 }
 
 /// GPU detection and initialization
 pub mod gpu {
     use super::*;
 
-    /// Detect available GPU devices
+    /// Detect all visible GPU devices by probing `/dev/nvidiaN` (NVIDIA) and
+    /// `/dev/dri/renderD{128+N}` (ROCm/AMD) for increasing `N`, returning one
+    /// [`Device`] per device found.
     pub fn detect_gpus() -> Result<Vec<Device>, ModelError> {
         let _sys = System::new();
 
-        // Check for NVIDIA GPUs
-        if std::path::Path::new("/dev/nvidia0").exists() {
-            return Ok(vec![Device::CUDA]);
+        let mut cuda_devices = Vec::new();
+        let mut ordinal = 0;
+        while std::path::Path::new(&format!("/dev/nvidia{ordinal}")).exists() {
+            cuda_devices.push(Device::CUDA(ordinal));
+            ordinal += 1;
+        }
+        if !cuda_devices.is_empty() {
+            return Ok(cuda_devices);
         }
 
-        // Check for AMD GPUs
+        // AMD GPUs each expose a DRI render node starting at minor number 128.
         if std::path::Path::new("/dev/kfd").exists() {
-            return Ok(vec![Device::ROCm]);
+            let mut rocm_devices = Vec::new();
+            let mut ordinal = 0;
+            while std::path::Path::new(&format!("/dev/dri/renderD{}", 128 + ordinal)).exists() {
+                rocm_devices.push(Device::ROCm(ordinal));
+                ordinal += 1;
+            }
+            if rocm_devices.is_empty() {
+                // /dev/kfd is present but render nodes couldn't be enumerated;
+                // assume a single device rather than reporting none.
+                rocm_devices.push(Device::ROCm(0));
+            }
+            return Ok(rocm_devices);
         }
 
         Ok(vec![])
     }
 
-    /// Initialize GPU context
+    /// Initialize GPU context for a given device
     pub fn init_gpu(device: &Device) -> Result<(), ModelError> {
         match device {
             Device::CPU => Ok(()),
-            Device::CUDA => {
+            Device::CUDA(ordinal) => {
                 #[cfg(feature = "cuda")]
                 {
-                    // Initialize CUDA context
+                    // Initialize CUDA context on the requested device
                     unsafe {
-                        let mut device_id = 0;
-                        let result = cuda_runtime_sys::cudaGetDevice(&mut device_id);
+                        let result = cuda_runtime_sys::cudaSetDevice(*ordinal as i32);
                         if result != cuda_runtime_sys::cudaError::cudaSuccess {
                             return Err(ModelError::GpuError(
-                                format!("Failed to get CUDA device: error {:?}", result)
+                                format!("Failed to set CUDA device {ordinal}: error {:?}", result)
                             ));
                         }
                     }
                 }
+                #[cfg(not(feature = "cuda"))]
+                let _ = ordinal;
                 Ok(())
             }
-            Device::ROCm => {
+            Device::ROCm(ordinal) => {
                 #[cfg(feature = "rocm")]
                 {
-                    // TODO: Initialize ROCm context
+                    // TODO: Initialize ROCm context on `ordinal`
                 }
+                #[cfg(not(feature = "rocm"))]
+                let _ = ordinal;
                 Ok(())
             }
         }
@@ -1,10 +1,17 @@
-use crate::{ModelConfig, ModelQuantizer, ModelFetcher, ModelQuantization, Result, CoreError};
+use crate::sharding::{self, WeightTensor};
+use crate::{Device, Model, ModelError, ModelFetcher, ModelQuantizer, ModelUploader, QuantizationConfig};
 use async_trait::async_trait;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use tch::{Device, Tensor, nn};
+use std::process::Command;
 use tracing::{info, warn};
 use huggingface_hub::api::sync::ApiBuilder;
 
+/// Fallback assumed free memory (bytes) for a device when it can't be
+/// queried, e.g. no `nvidia-smi` on PATH. Conservative enough to avoid
+/// over-packing a shard in that case.
+const DEFAULT_FREE_MEMORY_BYTES: u64 = 4 * 1_000_000_000;
+
 pub struct PyTorchQuantizer {
     api_token: String,
 }
@@ -14,103 +21,177 @@ impl PyTorchQuantizer {
         Self { api_token }
     }
 
-    fn get_device() -> Device {
-        if tch::Cuda::is_available() {
-            info!("CUDA is available, using GPU");
-            Device::Cuda(0)
-        } else {
-            warn!("CUDA is not available, falling back to CPU");
-            Device::Cpu
+    /// Map a core [`crate::Device`] to the `tch` device it corresponds to.
+    fn get_device(device: Device) -> tch::Device {
+        match device {
+            Device::CPU => tch::Device::Cpu,
+            Device::CUDA(ordinal) => tch::Device::Cuda(ordinal),
+            Device::ROCm(_) => {
+                warn!("tch has no ROCm backend here, falling back to CPU");
+                tch::Device::Cpu
+            }
         }
     }
+
+    /// Query free memory for each device via `nvidia-smi`, falling back to
+    /// [`DEFAULT_FREE_MEMORY_BYTES`] for devices it can't report on (e.g. no
+    /// `nvidia-smi` on PATH, or a ROCm device).
+    fn query_free_memory(devices: &[Device]) -> Vec<u64> {
+        devices
+            .iter()
+            .map(|device| match device {
+                Device::CUDA(ordinal) => Command::new("nvidia-smi")
+                    .args([
+                        "--query-gpu=memory.free",
+                        "--format=csv,noheader,nounits",
+                        &format!("--id={ordinal}"),
+                    ])
+                    .output()
+                    .ok()
+                    .and_then(|out| String::from_utf8(out.stdout).ok())
+                    .and_then(|s| s.trim().parse::<u64>().ok())
+                    .map(|mib| mib * 1_000_000)
+                    .unwrap_or(DEFAULT_FREE_MEMORY_BYTES),
+                _ => DEFAULT_FREE_MEMORY_BYTES,
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
 impl ModelFetcher for PyTorchQuantizer {
-    async fn fetch_model(&self, model_id: &str, cache_dir: &PathBuf) -> Result<PathBuf> {
-        info!("Fetching model {} to {:?}", model_id, cache_dir);
-        
-        // Create cache directory if it doesn't exist
-        tokio::fs::create_dir_all(cache_dir).await
-            .map_err(|e| CoreError::IOError(e))?;
-
-        // Initialize Hugging Face API client
+    async fn fetch(&self, name: &str, dest: &PathBuf) -> Result<Model, ModelError> {
+        info!("Fetching model {} to {:?}", name, dest);
+
+        tokio::fs::create_dir_all(dest).await.map_err(ModelError::IoError)?;
+
         let api = ApiBuilder::new()
             .with_token(self.api_token.clone())
             .build()
-            .map_err(|e| CoreError::ModelFetchError(e.to_string()))?;
-
-        // Download model files
-        let model_path = cache_dir.join(model_id.replace('/', "_"));
-        api.model(model_id).get(&model_path)
-            .map_err(|e| CoreError::ModelFetchError(e.to_string()))?;
-
-        Ok(model_path)
+            .map_err(|e| ModelError::FetchError(e.to_string()))?;
+
+        let model_path = dest.join(name.replace('/', "_"));
+        api.model(name.to_string())
+            .get(&model_path)
+            .map_err(|e| ModelError::FetchError(e.to_string()))?;
+
+        let size = tokio::fs::metadata(&model_path).await.map(|m| m.len()).unwrap_or(0);
+
+        Ok(Model {
+            id: name.to_string(),
+            name: name.to_string(),
+            path: model_path,
+            format: "pytorch".to_string(),
+            size,
+            shards: Vec::new(),
+        })
     }
 }
 
 #[async_trait]
-impl ModelQuantization for PyTorchQuantizer {
-    async fn quantize(&self, model_path: &PathBuf, bits: u8) -> Result<PathBuf> {
-        info!("Quantizing model at {:?} to {} bits", model_path, bits);
-
-        // Load the model
-        let device = Self::get_device();
-        let model = tch::CModule::load(model_path)
-            .map_err(|e| CoreError::QuantizationError(format!("Failed to load model: {}", e)))?;
-
-        // Prepare quantization config based on bit depth
-        let qconfig = match bits {
-            4 => nn::QConfigBuilder::new()
-                .with_activation_dtype(tch::Kind::QInt4)
-                .with_weight_dtype(tch::Kind::QInt4)
-                .build(),
-            8 => nn::QConfigBuilder::new()
-                .with_activation_dtype(tch::Kind::QInt8)
-                .with_weight_dtype(tch::Kind::QInt8)
-                .build(),
-            _ => return Err(CoreError::QuantizationError(
-                format!("Unsupported bit depth: {}", bits)
-            )),
+impl ModelQuantizer for PyTorchQuantizer {
+    async fn quantize(&self, model: &Model, config: QuantizationConfig) -> Result<Model, ModelError> {
+        info!("Quantizing model at {:?} to {} bits", model.path, config.bits);
+
+        let dtype = match config.bits {
+            4 => tch::Kind::QInt4,
+            8 => tch::Kind::QInt8,
+            _ => {
+                return Err(ModelError::QuantizationError(format!(
+                    "Unsupported bit depth: {}",
+                    config.bits
+                )))
+            }
+        };
+        let qmax = match config.bits {
+            4 => 7.0,
+            8 => 127.0,
+            _ => unreachable!(),
         };
 
-        // Quantize the model
-        let quantized_model = model.quantize(qconfig)
-            .map_err(|e| CoreError::QuantizationError(format!("Quantization failed: {}", e)))?;
-
-        // Save the quantized model
-        let output_path = model_path.with_extension(format!("quantized_{}_bit.pt", bits));
-        quantized_model.save(&output_path)
-            .map_err(|e| CoreError::QuantizationError(format!("Failed to save quantized model: {}", e)))?;
+        // Load the model and enumerate its weight tensors so they can be
+        // sharded across every visible device, rather than assuming a
+        // single GPU can hold the whole thing.
+        let module = tch::CModule::load(&model.path)
+            .map_err(|e| ModelError::QuantizationError(format!("Failed to load model: {}", e)))?;
+        let named_params = module
+            .named_parameters()
+            .map_err(|e| ModelError::QuantizationError(format!("Failed to read parameters: {}", e)))?;
+
+        let devices = if config.devices.is_empty() { vec![config.device] } else { config.devices.clone() };
+
+        let weight_tensors: Vec<WeightTensor> = named_params
+            .iter()
+            .map(|(name, t)| WeightTensor { name: name.clone(), bytes: t.numel() as u64 * 4 })
+            .collect();
+        let free_memory = Self::query_free_memory(&devices);
+        let plan: HashMap<String, Device> = sharding::plan_shards(
+            &weight_tensors,
+            &devices,
+            &free_memory,
+            sharding::DEFAULT_MAX_MEMORY_FRACTION,
+        )
+        .map_err(|e| ModelError::QuantizationError(e.to_string()))?
+        .into_iter()
+        .collect();
+
+        // Quantize each tensor on the device it was assigned to, then write
+        // the rounded values straight back into the module's own parameter
+        // storage (`named_parameters` aliases it, so `copy_` mutates the
+        // loaded module in place). This keeps the output a scriptable
+        // module `serve.rs` can load with `CModule::load_on_device`, rather
+        // than a bare tensor archive with no forward graph attached; the
+        // quantization error is baked into the stored float32 weights
+        // (a standard "fake quantization" / post-training-quant simulation)
+        // since `tch`'s `CModule` has no API to persist real packed
+        // quantized-dtype parameters back into the TorchScript graph.
+        for (name, tensor) in &named_params {
+            let target = plan.get(name).copied().unwrap_or(Device::CPU);
+            let tch_device = Self::get_device(target);
+            let on_device = tensor.to_device(tch_device);
+
+            let scale = f64::from(on_device.abs().max()) / qmax;
+            let scale = if scale == 0.0 { 1.0 } else { scale };
+            let quantized = on_device.quantize_per_tensor(scale, 0, dtype).dequantize();
+            tensor
+                .f_copy_(&quantized.to_device(tensor.device()))
+                .map_err(|e| ModelError::QuantizationError(format!("Failed to update parameter {name}: {e}")))?;
+        }
 
-        Ok(output_path)
+        let output_path = model.path.with_extension(format!("quantized_{}_bit.pt", config.bits));
+        module
+            .save(&output_path)
+            .map_err(|e| ModelError::QuantizationError(format!("Failed to save quantized model: {}", e)))?;
+
+        let size = tokio::fs::metadata(&output_path).await.map(|m| m.len()).unwrap_or(0);
+
+        Ok(Model {
+            id: model.id.clone(),
+            name: model.name.clone(),
+            path: output_path,
+            format: format!("quantized_{}_bit.pt", config.bits),
+            size,
+            shards: Vec::new(),
+        })
     }
 }
 
 #[async_trait]
-impl ModelQuantizer for PyTorchQuantizer {
-    async fn fetch_and_quantize(&self, config: &ModelConfig) -> Result<PathBuf> {
-        // First fetch the model
-        let model_path = self.fetch_model(&config.model_id, &config.cache_dir).await?;
-        
-        // Then quantize it
-        self.quantize(&model_path, config.quantization_bits).await
-    }
-
-    async fn upload_model(&self, model_path: &PathBuf, repo_id: &str) -> Result<()> {
-        info!("Uploading quantized model to {}", repo_id);
+impl ModelUploader for PyTorchQuantizer {
+    async fn upload(&self, model: &Model, repo: &str) -> Result<(), ModelError> {
+        info!("Uploading quantized model to {}", repo);
 
         let api = ApiBuilder::new()
             .with_token(self.api_token.clone())
             .build()
-            .map_err(|e| CoreError::ModelFetchError(e.to_string()))?;
+            .map_err(|e| ModelError::UploadError(e.to_string()))?;
 
-        api.create_repo(repo_id, None)
-            .map_err(|e| CoreError::ModelFetchError(format!("Failed to create repo: {}", e)))?;
+        api.create_repo(repo, None)
+            .map_err(|e| ModelError::UploadError(format!("Failed to create repo: {}", e)))?;
 
-        api.upload_file(repo_id, model_path)
-            .map_err(|e| CoreError::ModelFetchError(format!("Failed to upload model: {}", e)))?;
+        api.upload_file(repo, &model.path)
+            .map_err(|e| ModelError::UploadError(format!("Failed to upload model: {}", e)))?;
 
         Ok(())
     }
-} 
\ No newline at end of file
+}
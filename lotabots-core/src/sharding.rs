@@ -0,0 +1,121 @@
+//! Layer-wise (pipeline) sharding of a model's weight tensors across
+//! multiple devices, so quantizing a large checkpoint on several smaller
+//! GPUs doesn't hit `CUDA_ERROR_OUT_OF_MEMORY` on any single one of them.
+
+use crate::{Device, ModelError};
+
+/// The fraction of a device's free memory we're willing to fill with
+/// assigned tensors, leaving headroom for activations and framework
+/// overhead during quantization.
+pub const DEFAULT_MAX_MEMORY_FRACTION: f64 = 0.9;
+
+/// A named weight tensor and its size, as tracked for sharding purposes.
+#[derive(Debug, Clone)]
+pub struct WeightTensor {
+    pub name: String,
+    pub bytes: u64,
+}
+
+/// Greedily bin-pack `tensors` onto `devices`, keeping each device's
+/// assigned bytes under `max_fraction` of its corresponding entry in
+/// `free_memory_bytes`. Tensors are placed largest-first (worst-fit
+/// decreasing) onto the device with the most remaining headroom that still
+/// fits them.
+///
+/// When only one device is present, every tensor is assigned to it without
+/// a capacity check, preserving single-device behavior.
+pub fn plan_shards(
+    tensors: &[WeightTensor],
+    devices: &[Device],
+    free_memory_bytes: &[u64],
+    max_fraction: f64,
+) -> Result<Vec<(String, Device)>, ModelError> {
+    if devices.is_empty() {
+        return Err(ModelError::GpuError("no devices available for sharding".into()));
+    }
+
+    if devices.len() == 1 {
+        return Ok(tensors.iter().map(|t| (t.name.clone(), devices[0])).collect());
+    }
+
+    if free_memory_bytes.len() != devices.len() {
+        return Err(ModelError::GpuError(
+            "free_memory_bytes must have one entry per device".into(),
+        ));
+    }
+
+    let capacity: Vec<u64> = free_memory_bytes
+        .iter()
+        .map(|&free| (free as f64 * max_fraction) as u64)
+        .collect();
+    let mut used = vec![0u64; devices.len()];
+
+    let mut order: Vec<usize> = (0..tensors.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(tensors[i].bytes));
+
+    let mut assignments = Vec::with_capacity(tensors.len());
+    for i in order {
+        let tensor = &tensors[i];
+        let best = (0..devices.len())
+            .filter(|&d| used[d] + tensor.bytes <= capacity[d])
+            .max_by_key(|&d| capacity[d] - used[d]);
+
+        let chosen = best.ok_or_else(|| {
+            ModelError::GpuError(format!(
+                "tensor '{}' ({} bytes) does not fit on any device after sharding",
+                tensor.name, tensor.bytes
+            ))
+        })?;
+
+        used[chosen] += tensor.bytes;
+        assignments.push((tensor.name.clone(), devices[chosen]));
+    }
+
+    Ok(assignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(name: &str, bytes: u64) -> WeightTensor {
+        WeightTensor { name: name.to_string(), bytes }
+    }
+
+    #[test]
+    fn single_device_is_unchanged() {
+        let tensors = vec![tensor("a", 1_000_000), tensor("b", 2_000_000)];
+        let devices = vec![Device::CUDA(0)];
+        let plan = plan_shards(&tensors, &devices, &[10_000_000], DEFAULT_MAX_MEMORY_FRACTION).unwrap();
+        assert!(plan.iter().all(|(_, d)| *d == Device::CUDA(0)));
+    }
+
+    #[test]
+    fn spreads_large_model_over_small_devices() {
+        // 4 x 10GB devices, a 12GB model split into 4 equal 3GB shards.
+        let gb = 1_000_000_000u64;
+        let tensors: Vec<_> = (0..4).map(|i| tensor(&format!("layer{i}"), gb * 3)).collect();
+        let devices = vec![Device::CUDA(0), Device::CUDA(1), Device::CUDA(2), Device::CUDA(3)];
+        let free = vec![10 * gb; 4];
+
+        let plan = plan_shards(&tensors, &devices, &free, DEFAULT_MAX_MEMORY_FRACTION).unwrap();
+        assert_eq!(plan.len(), 4);
+
+        let mut per_device = [0u64; 4];
+        for (name, device) in &plan {
+            let idx = devices.iter().position(|d| d == device).unwrap();
+            let bytes = tensors.iter().find(|t| &t.name == name).unwrap().bytes;
+            per_device[idx] += bytes;
+            assert!(per_device[idx] as f64 <= 10.0 * gb as f64 * DEFAULT_MAX_MEMORY_FRACTION);
+        }
+    }
+
+    #[test]
+    fn errors_when_a_tensor_cannot_fit_anywhere() {
+        let tensors = vec![tensor("huge", 20_000_000_000)];
+        let devices = vec![Device::CUDA(0), Device::CUDA(1)];
+        let free = vec![10_000_000_000, 10_000_000_000];
+        let err = plan_shards(&tensors, &devices, &free, DEFAULT_MAX_MEMORY_FRACTION).unwrap_err();
+        assert!(matches!(err, ModelError::GpuError(_)));
+    }
+}
@@ -1,12 +1,23 @@
 //! Model fetching implementation for Hugging Face models.
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use async_trait::async_trait;
-use reqwest::Client;
+use futures_util::{stream, StreamExt};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tracing::{info, warn};
 use lotabots_core::{Model, ModelError, ModelFetcher};
 
+/// Name of the index file that describes a sharded safetensors repo.
+const SAFETENSORS_INDEX_FILENAME: &str = "model.safetensors.index.json";
+
+/// Max number of shard files downloaded concurrently.
+const MAX_CONCURRENT_SHARD_DOWNLOADS: usize = 4;
+
 /// Configuration for Hugging Face model fetching
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HuggingFaceConfig {
@@ -18,6 +29,41 @@ pub struct HuggingFaceConfig {
 
     /// Specific filename to fetch (defaults to "model.safetensors")
     pub filename: Option<String>,
+
+    /// Expected SHA-256 digest of the file. When set, overrides the digest
+    /// looked up from the repo's metadata. Verified after download.
+    pub expected_sha256: Option<String>,
+
+    /// Maximum number of download attempts before giving up (defaults to 3).
+    pub max_retries: Option<u32>,
+}
+
+/// A single entry in `GET /api/models/{name}`'s `siblings` list.
+#[derive(Debug, Deserialize)]
+struct HfSibling {
+    rfilename: String,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    lfs: Option<HfLfsPointer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HfLfsPointer {
+    sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HfModelInfo {
+    #[serde(default)]
+    siblings: Vec<HfSibling>,
+}
+
+/// The `model.safetensors.index.json` format: maps each tensor name to the
+/// shard filename it lives in.
+#[derive(Debug, Deserialize)]
+struct SafetensorsIndex {
+    weight_map: HashMap<String, String>,
 }
 
 /// Hugging Face model fetcher implementation
@@ -35,59 +81,187 @@ impl HuggingFaceFetcher {
 
     /// Build the model URL
     fn build_url(&self, model_name: &str) -> String {
-        let revision = self.config.revision.as_deref().unwrap_or("main");
         let filename = self.config.filename.as_deref().unwrap_or("model.safetensors");
+        self.file_url(model_name, filename)
+    }
 
+    /// Build the URL for an arbitrary file within the repo.
+    fn file_url(&self, model_name: &str, filename: &str) -> String {
+        let revision = self.config.revision.as_deref().unwrap_or("main");
         format!(
             "https://huggingface.co/{}/resolve/{}/{}",
             model_name, revision, filename
         )
     }
-}
 
-#[async_trait]
-impl ModelFetcher for HuggingFaceFetcher {
-    async fn fetch(&self, name: &str, dest: &PathBuf) -> Result<Model, ModelError> {
-        let url = self.build_url(name);
-        info!("Fetching model from {}", url);
+    fn authed(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.config.token {
+            Some(token) => request.header("Authorization", format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    /// Whether an HTTP error status is worth retrying: 5xx and 429 are,
+    /// other 4xx are not, so a bad token or missing file fails fast instead
+    /// of burning the whole retry budget.
+    fn classify_status(status: StatusCode) -> bool {
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
 
-        // Build request with optional authentication
-        let mut request = self.client.get(&url);
-        if let Some(token) = &self.config.token {
-            request = request.header("Authorization", format!("Bearer {}", token));
+    /// Stream `url` to `dest`, resuming from the current file length (if
+    /// any) via a `Range` request. Falls back to a full re-download if the
+    /// server replies `200 OK` instead of `206 Partial Content`.
+    ///
+    /// Returns `Err((retryable, error))`, where `retryable` is true for
+    /// network failures, 5xx, and 429, and false for other 4xx responses.
+    async fn stream_to_file(&self, url: &str, dest: &Path) -> Result<(), (bool, ModelError)> {
+        let existing_len = tokio::fs::metadata(dest).await.map(|m| m.len()).unwrap_or(0);
+
+        let mut request = self.authed(self.client.get(url));
+        if existing_len > 0 {
+            request = request.header("Range", format!("bytes={}-", existing_len));
         }
 
-        // Send request and get response
         let response = request.send().await
-            .map_err(|e| ModelError::FetchError(e.to_string()))?;
+            .map_err(|e| (true, ModelError::FetchError(e.to_string())))?;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        let status = response.status();
+        if !status.is_success() {
+            let retryable = Self::classify_status(status);
             let text = response.text().await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ModelError::FetchError(
+            return Err((retryable, ModelError::FetchError(
                 format!("HTTP {} - {}", status, text)
-            ));
+            )));
+        }
+
+        let mut file = if status == StatusCode::PARTIAL_CONTENT {
+            info!("Resuming download of {:?} from byte {}", dest, existing_len);
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(dest)
+                .await
+                .map_err(|e| (false, ModelError::IoError(e)))?
+        } else {
+            if existing_len > 0 {
+                warn!("Server ignored Range request for {:?}, restarting download", dest);
+            }
+            tokio::fs::File::create(dest).await.map_err(|e| (false, ModelError::IoError(e)))?
+        };
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| (true, ModelError::FetchError(e.to_string())))?;
+            file.write_all(&chunk).await.map_err(|e| (false, ModelError::IoError(e)))?;
+        }
+        file.flush().await.map_err(|e| (false, ModelError::IoError(e)))?;
+
+        Ok(())
+    }
+
+    /// Retry [`Self::stream_to_file`] with exponential backoff, resuming
+    /// from wherever the previous attempt left off. Only retries transient
+    /// failures (network errors, 5xx, 429); a permanent error (e.g. 401/404)
+    /// is returned immediately.
+    async fn fetch_with_retry(&self, url: &str, dest: &Path) -> Result<(), ModelError> {
+        let max_retries = self.config.max_retries.unwrap_or(3).max(1);
+        let mut delay = Duration::from_secs(1);
+
+        for attempt in 1..=max_retries {
+            match self.stream_to_file(url, dest).await {
+                Ok(()) => return Ok(()),
+                Err((_, e)) if attempt == max_retries => return Err(e),
+                Err((false, e)) => return Err(e),
+                Err((true, e)) => {
+                    warn!(
+                        "Download attempt {}/{} failed ({}), retrying in {:?}",
+                        attempt, max_retries, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+
+        unreachable!("loop always returns on the final attempt")
+    }
+
+    /// Resolve the SHA-256 digest we expect the downloaded file to have:
+    /// `expected_sha256` if set, otherwise the digest reported by the HF
+    /// model metadata API for the fetched filename. Requests `blobs=true`
+    /// since the default `/api/models/{name}` response omits file hashes.
+    async fn resolve_expected_sha256(&self, model_name: &str) -> Option<String> {
+        if self.config.expected_sha256.is_some() {
+            return self.config.expected_sha256.clone();
         }
 
-        // Get content length if available
-        let size = response.content_length()
-            .unwrap_or(0);
+        let filename = self.config.filename.as_deref().unwrap_or("model.safetensors");
+        let api_url = format!("https://huggingface.co/api/models/{}?blobs=true", model_name);
+        let response = match self.authed(self.client.get(&api_url)).send().await {
+            Ok(r) if r.status().is_success() => r,
+            _ => {
+                warn!("Could not fetch metadata for {} to verify checksum", model_name);
+                return None;
+            }
+        };
+
+        let info: HfModelInfo = match response.json().await {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("Could not parse metadata for {}: {}", model_name, e);
+                return None;
+            }
+        };
+
+        info.siblings
+            .into_iter()
+            .find(|s| s.rfilename == filename)
+            .and_then(|s| s.lfs.and_then(|lfs| lfs.sha256).or(s.sha256))
+    }
+
+    /// Verify `path` hashes to `expected` (case-insensitive hex SHA-256).
+    async fn verify_sha256(path: &Path, expected: &str) -> Result<(), ModelError> {
+        let mut file = tokio::fs::File::open(path).await.map_err(ModelError::IoError)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 1 << 20];
+        loop {
+            let n = file.read(&mut buf).await.map_err(ModelError::IoError)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        let actual = format!("{:x}", hasher.finalize());
+        if actual.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(ModelError::FetchError(format!(
+                "SHA-256 mismatch for {:?}: expected {}, got {}",
+                path, expected, actual
+            )))
+        }
+    }
 
-        // Stream response to file
-        let bytes = response.bytes().await
-            .map_err(|e| ModelError::FetchError(e.to_string()))?;
+    /// Fetch the single file named by `config.filename` (or the default
+    /// `model.safetensors`) to `dest`.
+    async fn fetch_single(&self, name: &str, dest: &PathBuf) -> Result<Model, ModelError> {
+        let url = self.build_url(name);
+        info!("Fetching model from {}", url);
 
-        // Create parent directories if needed
         if let Some(parent) = dest.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(ModelError::IoError)?;
+            std::fs::create_dir_all(parent).map_err(ModelError::IoError)?;
         }
 
-        // Write to file
-        std::fs::write(dest, bytes)
-            .map_err(ModelError::IoError)?;
+        self.fetch_with_retry(&url, dest).await?;
+
+        if let Some(expected) = self.resolve_expected_sha256(name).await {
+            Self::verify_sha256(dest, &expected).await?;
+        } else {
+            warn!("No expected SHA-256 available for {}, skipping verification", name);
+        }
 
+        let size = tokio::fs::metadata(dest).await.map(|m| m.len()).unwrap_or(0);
         info!("Model downloaded to {:?}", dest);
 
         Ok(Model {
@@ -96,8 +270,93 @@ impl ModelFetcher for HuggingFaceFetcher {
             path: dest.clone(),
             format: "safetensors".to_string(),
             size,
+            shards: Vec::new(),
         })
     }
+
+    /// Fetch a repo split across multiple safetensors shards, described by
+    /// `model.safetensors.index.json`. `dest_dir` is the directory all
+    /// shards (and the index) are downloaded into.
+    ///
+    /// The index is fetched to a side path first, outside `dest_dir`: until
+    /// it parses successfully we don't know the repo is actually sharded,
+    /// and `dest_dir` may instead be the single-file destination the caller
+    /// will fall back to via [`Self::fetch_single`]. Only once sharding is
+    /// confirmed do we turn `dest_dir` into a directory.
+    async fn fetch_sharded(&self, name: &str, dest_dir: &PathBuf) -> Result<Model, ModelError> {
+        let index_url = self.file_url(name, SAFETENSORS_INDEX_FILENAME);
+        let temp_index_path = std::env::temp_dir()
+            .join(format!("lotabots-fetch-{}-{}", name.replace('/', "_"), SAFETENSORS_INDEX_FILENAME));
+        info!("Fetching safetensors index from {}", index_url);
+        self.fetch_with_retry(&index_url, &temp_index_path).await?;
+
+        let index_bytes = tokio::fs::read(&temp_index_path).await.map_err(ModelError::IoError)?;
+        let index: Result<SafetensorsIndex, _> = serde_json::from_slice(&index_bytes);
+        let index = match index {
+            Ok(index) => index,
+            Err(e) => {
+                tokio::fs::remove_file(&temp_index_path).await.ok();
+                return Err(ModelError::FetchError(format!("invalid safetensors index: {}", e)));
+            }
+        };
+
+        // The repo is confirmed sharded: now it's safe to create dest_dir
+        // as a directory and move the index alongside the shards.
+        tokio::fs::create_dir_all(dest_dir).await.map_err(ModelError::IoError)?;
+        let index_path = dest_dir.join(SAFETENSORS_INDEX_FILENAME);
+        tokio::fs::rename(&temp_index_path, &index_path).await.map_err(ModelError::IoError)?;
+
+        let mut shard_names: Vec<String> = index.weight_map.into_values().collect();
+        shard_names.sort();
+        shard_names.dedup();
+        info!("Repo {} has {} safetensors shards", name, shard_names.len());
+
+        let shard_paths: Vec<PathBuf> = stream::iter(shard_names.into_iter().map(|shard| {
+            let url = self.file_url(name, &shard);
+            let dest = dest_dir.join(&shard);
+            async move {
+                self.fetch_with_retry(&url, &dest).await?;
+                Ok::<PathBuf, ModelError>(dest)
+            }
+        }))
+        .buffer_unordered(MAX_CONCURRENT_SHARD_DOWNLOADS)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let mut size = 0u64;
+        for shard in &shard_paths {
+            size += tokio::fs::metadata(shard).await.map(|m| m.len()).unwrap_or(0);
+        }
+
+        Ok(Model {
+            id: name.to_string(),
+            name: name.to_string(),
+            path: dest_dir.clone(),
+            format: "safetensors-sharded".to_string(),
+            size,
+            shards: shard_paths,
+        })
+    }
+}
+
+#[async_trait]
+impl ModelFetcher for HuggingFaceFetcher {
+    async fn fetch(&self, name: &str, dest: &PathBuf) -> Result<Model, ModelError> {
+        if self.config.filename.is_some() {
+            return self.fetch_single(name, dest).await;
+        }
+
+        match self.fetch_sharded(name, dest).await {
+            Ok(model) => Ok(model),
+            Err(ModelError::FetchError(msg)) if msg.contains("404") => {
+                info!("{} has no safetensors index, falling back to a single file", name);
+                self.fetch_single(name, dest).await
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -111,6 +370,8 @@ mod tests {
             token: None,
             revision: None,
             filename: Some("config.json".into()),
+            expected_sha256: None,
+            max_retries: None,
         };
 
         let fetcher = HuggingFaceFetcher::new(config);
@@ -0,0 +1,289 @@
+//! `cargo xtask bench` workload runner: fetches and quantizes each job in a
+//! workload file through the real `ModelFetcher`/`ModelQuantizer` traits,
+//! recording per-stage timing, peak RSS, and output size for regression
+//! tracking across commits.
+
+use lotabots_core::gpu;
+use lotabots_core::{Device, Model, ModelFetcher, ModelQuantizer, QuantizationConfig};
+use lotabots_fetch::{HuggingFaceConfig, HuggingFaceFetcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use sysinfo::{CpuRefreshKind, ProcessRefreshKind, RefreshKind, System};
+use tokio::time::{interval, Duration};
+use tracing::{info, warn};
+
+/// How often [`with_peak_rss`] samples RSS while a stage runs.
+const RSS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A single workload file: a list of jobs to run.
+#[derive(Debug, Deserialize)]
+pub struct Workload {
+    pub jobs: Vec<BenchJob>,
+}
+
+/// One fetch+quantize job to benchmark.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchJob {
+    pub name: String,
+    pub model_id: String,
+    pub bits: u8,
+    #[serde(default)]
+    pub gguf_type: Option<String>,
+    #[serde(default = "default_device")]
+    pub device: String,
+    #[serde(default)]
+    pub expected_output_size_bytes: Option<u64>,
+}
+
+fn default_device() -> String {
+    "cpu".to_string()
+}
+
+/// Wall-clock and memory measurements for a single pipeline stage.
+#[derive(Debug, Serialize)]
+pub struct StageTiming {
+    pub stage: String,
+    pub duration_ms: u128,
+    pub peak_rss_bytes: u64,
+}
+
+/// Environment snapshot recorded alongside every run, so results are
+/// comparable across commits and machines.
+#[derive(Debug, Serialize)]
+pub struct EnvInfo {
+    pub cpu_model: String,
+    pub cpu_cores: usize,
+    pub gpus: Vec<String>,
+    pub git_commit: String,
+}
+
+/// The full result of benchmarking one job.
+#[derive(Debug, Serialize)]
+pub struct BenchResult {
+    pub job_name: String,
+    pub model_id: String,
+    pub env: EnvInfo,
+    pub stages: Vec<StageTiming>,
+    pub output_size_bytes: Option<u64>,
+    pub expected_output_size_bytes: Option<u64>,
+    pub skipped_reason: Option<String>,
+}
+
+fn collect_env_info() -> EnvInfo {
+    let mut sys = System::new_with_specifics(
+        RefreshKind::new()
+            .with_cpu(CpuRefreshKind::everything())
+            .with_processes(ProcessRefreshKind::everything()),
+    );
+    sys.refresh_cpu();
+
+    let cpu_model = sys
+        .cpus()
+        .first()
+        .map(|c| c.brand().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let cpu_cores = sys.cpus().len();
+
+    let gpus = gpu::detect_gpus()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|d| format!("{:?}", d))
+        .collect();
+
+    let git_commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    EnvInfo { cpu_model, cpu_cores, gpus, git_commit }
+}
+
+fn rss_bytes() -> u64 {
+    let pid = sysinfo::get_current_pid().ok();
+    let mut sys = System::new();
+    sys.refresh_processes();
+    pid.and_then(|pid| sys.process(pid)).map(|p| p.memory()).unwrap_or(0)
+}
+
+/// Run `fut`, polling the process' RSS every [`RSS_POLL_INTERVAL`] while it's
+/// in flight, and return its output alongside the high-water mark observed
+/// during that window. A single snapshot taken after the stage finishes
+/// would miss any transient allocation that already freed by then, which
+/// defeats the point of measuring a *peak*.
+async fn with_peak_rss<F: std::future::Future>(fut: F) -> (F::Output, u64) {
+    let peak = Arc::new(AtomicU64::new(rss_bytes()));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let poller = tokio::spawn({
+        let peak = peak.clone();
+        let stop = stop.clone();
+        async move {
+            let mut ticker = interval(RSS_POLL_INTERVAL);
+            while !stop.load(Ordering::Relaxed) {
+                ticker.tick().await;
+                peak.fetch_max(rss_bytes(), Ordering::Relaxed);
+            }
+        }
+    });
+
+    let result = fut.await;
+    stop.store(true, Ordering::Relaxed);
+    let _ = poller.await;
+
+    (result, peak.load(Ordering::Relaxed))
+}
+
+fn parse_device(name: &str) -> Device {
+    match name {
+        "cuda" | "cuda:0" => Device::CUDA(0),
+        "rocm" | "rocm:0" => Device::ROCm(0),
+        _ => Device::CPU,
+    }
+}
+
+/// Whether to run the real fetch/quantize pipeline: true when `HF_API_TOKEN`
+/// is set. Network reachability itself is not probed. Offline CI and local
+/// builds without an HF token skip gracefully rather than fail.
+fn network_available() -> bool {
+    env_hf_token().is_some()
+}
+
+fn env_hf_token() -> Option<String> {
+    std::env::var("HF_API_TOKEN").ok()
+}
+
+async fn run_job(job: &BenchJob, env: &EnvInfo) -> BenchResult {
+    if !network_available() {
+        warn!("Skipping '{}': no HF_API_TOKEN / network access configured", job.name);
+        return BenchResult {
+            job_name: job.name.clone(),
+            model_id: job.model_id.clone(),
+            env: collect_env_info_or(env),
+            stages: Vec::new(),
+            output_size_bytes: None,
+            expected_output_size_bytes: job.expected_output_size_bytes,
+            skipped_reason: Some("no network/HF token available".to_string()),
+        };
+    }
+
+    let mut stages = Vec::new();
+    let cache_dir = std::env::temp_dir().join("lotabots-xtask-bench").join(&job.name);
+    let dest = cache_dir.join("model.safetensors");
+
+    let fetch_started = Instant::now();
+    let fetcher = HuggingFaceFetcher::new(HuggingFaceConfig {
+        token: env_hf_token(),
+        revision: None,
+        filename: None,
+        expected_sha256: None,
+        max_retries: Some(3),
+    });
+
+    let (fetch_result, fetch_peak_rss) = with_peak_rss(fetcher.fetch(&job.model_id, &dest)).await;
+    let model = match fetch_result {
+        Ok(model) => model,
+        Err(e) => {
+            return BenchResult {
+                job_name: job.name.clone(),
+                model_id: job.model_id.clone(),
+                env: collect_env_info_or(env),
+                stages,
+                output_size_bytes: None,
+                expected_output_size_bytes: job.expected_output_size_bytes,
+                skipped_reason: Some(format!("fetch failed: {e}")),
+            };
+        }
+    };
+    stages.push(StageTiming {
+        stage: "fetch".to_string(),
+        duration_ms: fetch_started.elapsed().as_millis(),
+        peak_rss_bytes: fetch_peak_rss,
+    });
+
+    let quantize_started = Instant::now();
+    let mut params = HashMap::new();
+    if let Some(gguf_type) = &job.gguf_type {
+        params.insert("gguf_type".to_string(), gguf_type.clone());
+    }
+    let device = parse_device(&job.device);
+    let config = QuantizationConfig {
+        bits: job.bits,
+        mixed_precision: false,
+        device,
+        devices: vec![device],
+        params,
+    };
+
+    let quantizer = lotabots_core::gguf::GgufQuantizer::new();
+    let (output, quantize_peak_rss) = with_peak_rss(quantize_with(&quantizer, &model, config)).await;
+    stages.push(StageTiming {
+        stage: "quantize".to_string(),
+        duration_ms: quantize_started.elapsed().as_millis(),
+        peak_rss_bytes: quantize_peak_rss,
+    });
+
+    let output_size_bytes = match &output {
+        Ok(m) => Some(m.size),
+        Err(e) => {
+            warn!("quantize failed for '{}': {}", job.name, e);
+            None
+        }
+    };
+
+    BenchResult {
+        job_name: job.name.clone(),
+        model_id: job.model_id.clone(),
+        env: collect_env_info_or(env),
+        stages,
+        output_size_bytes,
+        expected_output_size_bytes: job.expected_output_size_bytes,
+        skipped_reason: None,
+    }
+}
+
+async fn quantize_with(
+    quantizer: &lotabots_core::gguf::GgufQuantizer,
+    model: &Model,
+    config: QuantizationConfig,
+) -> Result<Model, lotabots_core::ModelError> {
+    quantizer.quantize(model, config).await
+}
+
+fn collect_env_info_or(env: &EnvInfo) -> EnvInfo {
+    EnvInfo {
+        cpu_model: env.cpu_model.clone(),
+        cpu_cores: env.cpu_cores,
+        gpus: env.gpus.clone(),
+        git_commit: env.git_commit.clone(),
+    }
+}
+
+/// Run every job in `workload_path`, returning one [`BenchResult`] per job.
+pub async fn run_workload(workload_path: &Path) -> anyhow::Result<Vec<BenchResult>> {
+    let bytes = std::fs::read(workload_path)?;
+    let workload: Workload = serde_json::from_slice(&bytes)?;
+    let env = collect_env_info();
+
+    let mut results = Vec::with_capacity(workload.jobs.len());
+    for job in &workload.jobs {
+        info!("Running bench job '{}'", job.name);
+        results.push(run_job(job, &env).await);
+    }
+
+    Ok(results)
+}
+
+/// POST `results` as JSON to `url` for comparison against a baseline.
+pub async fn publish_results(url: &str, results: &[BenchResult]) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    client.post(url).json(results).send().await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+//! Developer task runner for the Lotabots workspace (`cargo xtask ...`).
+
+mod bench;
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Lotabots developer task runner", long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a fetch/quantize performance workload and report timings.
+    Bench {
+        /// Path to a workload JSON file describing the jobs to run.
+        #[arg(long, default_value = "xtask/workloads/bert-base-uncased.json")]
+        workload: PathBuf,
+
+        /// Write the JSON report to this file; it is also printed to stdout.
+        #[arg(long, default_value = "bench_output.txt")]
+        output: PathBuf,
+
+        /// POST the JSON report to this URL for comparison against a baseline.
+        #[arg(long)]
+        results_url: Option<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let args = Args::parse();
+
+    match args.command {
+        Command::Bench { workload, output, results_url } => {
+            let results = bench::run_workload(&workload).await?;
+            let report = serde_json::to_string_pretty(&results)?;
+
+            std::fs::write(&output, &report)?;
+            println!("{}", report);
+
+            if let Some(url) = results_url {
+                bench::publish_results(&url, &results).await?;
+            }
+        }
+    }
+
+    Ok(())
+}